@@ -6,51 +6,270 @@ use std::{
 use clap::Parser;
 use git2::Repository;
 use regex::Regex;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 #[derive(Parser, Debug)]
 struct Cli {
     repo_url: Option<String>,
+
+    /// Forge `repo_url` points at. When `repo_url` is omitted, the forge is
+    /// auto-detected from `origin` instead (see `--host`).
+    #[arg(long, value_enum, default_value_t = Remote::Github)]
+    remote: Remote,
+
+    /// Hostname of a self-hosted GitHub Enterprise/GitLab instance to
+    /// recognize when auto-detecting the repo from `origin`, in addition to
+    /// github.com/gitlab.com. Interpreted according to `--remote`.
+    #[arg(long)]
+    host: Option<String>,
+
+    /// Resume a specific session instead of the repo's most recent one.
+    #[arg(long)]
+    session: Option<String>,
+
+    /// Start a fresh session even if a previous one exists for this repo.
+    #[arg(long)]
+    new_session: bool,
+
+    /// Question to ask about the repo. Defaults to the Shuttle-conversion
+    /// prompt; pass your own to ask a follow-up within a resumed session.
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Path to config.toml with API tokens and the webhook secret.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Output the conversion plan as rendered Markdown or as validated JSON.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Markdown)]
+    format: OutputFormat,
+
+    /// Print the reply as it streams in instead of waiting for the full
+    /// response. Ignored in JSON mode, which needs the complete body to parse.
+    #[arg(long)]
+    stream: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum OutputFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Run a webhook server that reindexes on GitHub push events.
+    Serve {
+        /// Address to bind the webhook listener to.
+        #[arg(long, default_value = "0.0.0.0:8080")]
+        addr: String,
+
+        /// Branch to reindex on. Pushes to any other branch are ignored.
+        #[arg(long, default_value = "main")]
+        branch: String,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let repository = if let Some(repo_url) = cli.repo_url {
-        repo_url
+    let config = Config::load(cli.config.as_ref())?;
+    let host_override = cli.host.as_deref().map(|host| (host, cli.remote));
+
+    if let Some(Command::Serve { addr, branch }) = cli.command {
+        let (remote, repository) = if let Some(repo_url) = cli.repo_url {
+            (cli.remote, repo_url)
+        } else {
+            get_git_repo(host_override)?
+        };
+
+        let repo = GreptileRepository {
+            remote,
+            branch,
+            repository,
+        };
+
+        let greptile = match &config {
+            Some(config) => GreptileClient::from_config(config)?,
+            None => GreptileClient::from_env()?,
+        };
+
+        let webhook_secret = match &config {
+            Some(config) => Secret::new(
+                config
+                    .webhook
+                    .as_ref()
+                    .ok_or("config.toml is missing a [webhook] secret")?
+                    .secret
+                    .clone(),
+            ),
+            None => Secret::new(std::env::var("GITHUB_WEBHOOK_SECRET")?),
+        };
+
+        return serve(addr, repo, greptile, webhook_secret).await;
+    }
+
+    let (remote, repository) = if let Some(repo_url) = cli.repo_url {
+        (cli.remote, repo_url)
     } else {
-        get_git_repo()?
-        // "shuttle-hq/zero-to-production-newsletter-api".to_string()
+        get_git_repo(host_override)?
+        // ("github".parse().unwrap(), "shuttle-hq/zero-to-production-newsletter-api".to_string())
     };
 
     let repo = GreptileRepository {
-        remote: "github".to_string(),
+        remote,
         branch: "main".to_string(),
         repository,
     };
 
     let repo_id = repo.as_repo_id();
 
-    let greptile = GreptileClient::from_env()?;
+    let greptile = match &config {
+        Some(config) => GreptileClient::from_config(config)?,
+        None => GreptileClient::from_env()?,
+    };
 
     // let req: GreptileIndexRequest = repo.clone().into();
 
     // greptile.index_repo(req).await?;
 
-    let query = PROMPT.to_string();
+    let db = DbCtx::open("state.db")?;
+
+    let (session_id, mut messages) = if let Some(session_id) = cli.session {
+        let messages = db.load_messages(&session_id)?;
+        (session_id, messages)
+    } else if cli.new_session {
+        (Uuid::new_v4().to_string(), Vec::new())
+    } else if let Some(session_id) = db.latest_session_for_repo(&repo_id)? {
+        let messages = db.load_messages(&session_id)?;
+        (session_id, messages)
+    } else {
+        (Uuid::new_v4().to_string(), Vec::new())
+    };
 
-    let req = GreptileQueryRequest::new(repo, GreptileMessage::user(query));
+    db.ensure_session(&session_id, &repo_id)?;
 
-    let response = greptile.query_repo(req).await?;
+    let query = cli.query.unwrap_or_else(|| PROMPT.to_string());
 
-    termimad::print_text(&response);
+    let user_message = GreptileMessage::user(query);
+    db.save_message(&session_id, &user_message)?;
+    messages.push(user_message);
+
+    let mut req = GreptileQueryRequest::with_messages(repo, messages);
+    req.session_id = session_id.clone();
+
+    let stream = cli.stream && matches!(cli.format, OutputFormat::Markdown);
+
+    let response = if stream {
+        greptile.query_repo_streaming(req).await?
+    } else {
+        greptile.query_repo(req).await?
+    };
+
+    db.save_message(&session_id, &GreptileMessage::assistant(response.clone()))?;
+
+    match cli.format {
+        // Streaming mode already printed the reply as it arrived.
+        OutputFormat::Markdown if !stream => termimad::print_text(&response),
+        OutputFormat::Markdown => {}
+        OutputFormat::Json => {
+            let plan = ConversionPlan::from_markdown(&response)?;
+            println!("{}", serde_json::to_string_pretty(&plan)?);
+        }
+    }
 
     Ok(())
 }
 
-fn get_git_repo() -> Result<String, Box<dyn std::error::Error>> {
+/// SQLite-backed storage for query sessions and their message history.
+struct DbCtx {
+    conn: rusqlite::Connection,
+}
+
+impl DbCtx {
+    fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(path)?;
+
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id         TEXT PRIMARY KEY,
+                repo_id    TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            CREATE TABLE IF NOT EXISTS messages (
+                id         TEXT PRIMARY KEY,
+                session_id TEXT NOT NULL REFERENCES sessions(id),
+                role       TEXT NOT NULL,
+                content    TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );",
+        )?;
+
+        Ok(Self { conn })
+    }
+
+    fn ensure_session(&self, session_id: &str, repo_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO sessions (id, repo_id) VALUES (?1, ?2)",
+            rusqlite::params![session_id, repo_id],
+        )?;
+
+        Ok(())
+    }
+
+    fn latest_session_for_repo(&self, repo_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id FROM sessions WHERE repo_id = ?1 ORDER BY created_at DESC LIMIT 1",
+        )?;
+
+        let session_id = stmt
+            .query_row(rusqlite::params![repo_id], |row| row.get(0))
+            .ok();
+
+        Ok(session_id)
+    }
+
+    fn load_messages(&self, session_id: &str) -> Result<Vec<GreptileMessage>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, role, content FROM messages WHERE session_id = ?1 ORDER BY created_at ASC",
+        )?;
+
+        let messages = stmt
+            .query_map(rusqlite::params![session_id], |row| {
+                let role: String = row.get(1)?;
+
+                Ok(GreptileMessage {
+                    id: row.get(0)?,
+                    content: row.get(2)?,
+                    role: match role.as_str() {
+                        "system" => Role::System,
+                        "assistant" => Role::Assistant,
+                        _ => Role::User,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(messages)
+    }
+
+    fn save_message(&self, session_id: &str, message: &GreptileMessage) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute(
+            "INSERT INTO messages (id, session_id, role, content) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![message.id, session_id, message.role.to_string(), message.content],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn get_git_repo(host_override: Option<(&str, Remote)>) -> Result<(Remote, String), Box<dyn std::error::Error>> {
     let repository = Repository::open(".")?;
 
     let remote = repository.find_remote("origin")?;
@@ -59,45 +278,289 @@ fn get_git_repo() -> Result<String, Box<dyn std::error::Error>> {
         return Err("Could not find remote URL for origin remote".into());
     };
 
-    let regex = Regex::new(r#"https?:\/\/(?:www\.)?github\.com\/([\w.-]+\/[\w.-]+)\.git"#)?;
+    if let Some((host, remote)) = host_override {
+        if let Some(repository) = parse_forge_url(host, repo_url)? {
+            return Ok((remote, repository));
+        }
+    }
+
+    if let Some(repository) = parse_forge_url("github.com", repo_url)? {
+        return Ok((Remote::Github, repository));
+    }
+
+    if let Some(repository) = parse_forge_url("gitlab.com", repo_url)? {
+        return Ok((Remote::Gitlab, repository));
+    }
+
+    Err(format!("Could not determine the forge for remote URL: {repo_url}").into())
+}
+
+/// Extracts the repo path from an HTTPS or SSH remote URL for `host`.
+fn parse_forge_url(host: &str, repo_url: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let host = regex::escape(host);
+    let regex = Regex::new(&format!(r#"{host}[:/]((?:[\w.-]+/)*[\w.-]+?)(?:\.git)?$"#))?;
+
+    Ok(regex.captures(repo_url).map(|caps| caps[1].to_string()))
+}
 
-    let caps = regex.captures(&repo_url).unwrap();
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 
-    Ok(caps.get(0).unwrap().as_str().to_string())
+#[derive(Clone)]
+struct WebhookState {
+    greptile: std::sync::Arc<GreptileClient>,
+    repo: GreptileRepository,
+    branch: String,
+    webhook_secret: std::sync::Arc<Secret<String>>,
+}
+
+async fn serve(
+    addr: String,
+    repo: GreptileRepository,
+    greptile: GreptileClient,
+    webhook_secret: Secret<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let branch = repo.branch.clone();
+
+    let state = WebhookState {
+        greptile: std::sync::Arc::new(greptile),
+        repo,
+        branch,
+        webhook_secret: std::sync::Arc::new(webhook_secret),
+    };
+
+    let app = axum::Router::new()
+        .route("/webhook", axum::routing::post(handle_webhook))
+        .with_state(state);
+
+    println!("Listening for GitHub webhooks on {addr}");
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+/// Verifies a GitHub `X-Hub-Signature-256` header (`sha256=<hex hmac>`)
+/// against the raw request body, using the constant-time comparison built
+/// into `Mac::verify_slice`.
+fn verify_github_signature(secret: &[u8], body: &[u8], signature_header: &str) -> bool {
+    use hmac::Mac;
+
+    let Some(signature) = signature_header.strip_prefix("sha256=") else {
+        return false;
+    };
+
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let Ok(mut mac) = HmacSha256::new_from_slice(secret) else {
+        return false;
+    };
+
+    mac.update(body);
+
+    mac.verify_slice(&signature).is_ok()
+}
+
+async fn handle_webhook(
+    axum::extract::State(state): axum::extract::State<WebhookState>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> axum::http::StatusCode {
+    let Some(signature_header) = headers
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+    else {
+        return axum::http::StatusCode::UNAUTHORIZED;
+    };
+
+    if !verify_github_signature(
+        state.webhook_secret.expose_secret().as_bytes(),
+        &body,
+        signature_header,
+    ) {
+        return axum::http::StatusCode::UNAUTHORIZED;
+    }
+
+    let is_push = headers
+        .get("X-GitHub-Event")
+        .and_then(|v| v.to_str().ok())
+        == Some("push");
+
+    if !is_push {
+        return axum::http::StatusCode::OK;
+    }
+
+    let Ok(payload) = serde_json::from_slice::<serde_json::Value>(&body) else {
+        return axum::http::StatusCode::BAD_REQUEST;
+    };
+
+    let pushed_ref = payload.get("ref").and_then(|v| v.as_str());
+
+    if pushed_ref != Some(&format!("refs/heads/{}", state.branch)) {
+        return axum::http::StatusCode::OK;
+    }
+
+    let Some(full_name) = payload
+        .get("repository")
+        .and_then(|r| r.get("full_name"))
+        .and_then(|v| v.as_str())
+    else {
+        return axum::http::StatusCode::BAD_REQUEST;
+    };
+
+    let mut repo = state.repo.clone();
+    repo.repository = full_name.to_string();
+
+    let req = GreptileIndexRequest::from(repo);
+
+    match state.greptile.index_repo(req).await {
+        Ok(()) => axum::http::StatusCode::OK,
+        Err(err) => {
+            eprintln!("Failed to reindex {full_name}: {err}");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Typed view over `config.toml`.
+#[derive(Deserialize)]
+struct Config {
+    greptile: GreptileConfigSection,
+    github: Option<GithubConfigSection>,
+    gitlab: Option<GitlabConfigSection>,
+    webhook: Option<WebhookConfigSection>,
+}
+
+#[derive(Deserialize)]
+struct GreptileConfigSection {
+    api_token: String,
+}
+
+#[derive(Deserialize)]
+struct GithubConfigSection {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct GitlabConfigSection {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct WebhookConfigSection {
+    secret: String,
+}
+
+impl Config {
+    /// Loads `path`, or the default `config.toml` if present; `None` if neither.
+    fn load(path: Option<&PathBuf>) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = match path {
+            Some(path) => path.clone(),
+            None => {
+                let default = PathBuf::from("config.toml");
+                if !default.exists() {
+                    return Ok(None);
+                }
+                default
+            }
+        };
+
+        let contents = std::fs::read_to_string(&path)?;
+        let config: Config = toml::from_str(&contents)?;
+
+        Ok(Some(config))
+    }
 }
 
 struct GreptileClient {
     client: reqwest::Client,
-    github_token: String,
-    greptile_api_token: String,
+    github_token: Option<Secret<String>>,
+    gitlab_token: Option<Secret<String>>,
+    greptile_api_token: Secret<String>,
 }
 
 impl GreptileClient {
-    fn new(github_token: String, greptile_api_token: String) -> Self {
+    fn new(
+        github_token: Option<Secret<String>>,
+        gitlab_token: Option<Secret<String>>,
+        greptile_api_token: Secret<String>,
+    ) -> Self {
         Self {
             client: reqwest::Client::new(),
             github_token,
+            gitlab_token,
             greptile_api_token,
         }
     }
 
     fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        let github_token = std::env::var("GITHUB_ACCESS_TOKEN")?;
-        let greptile_api_token = std::env::var("GREPTILE_API_TOKEN")?;
+        let github_token = std::env::var("GITHUB_ACCESS_TOKEN").ok().map(Secret::new);
+        let gitlab_token = std::env::var("GITLAB_ACCESS_TOKEN").ok().map(Secret::new);
+        let greptile_api_token = Secret::new(std::env::var("GREPTILE_API_TOKEN")?);
+
+        if github_token.is_none() && gitlab_token.is_none() {
+            return Err("Neither GITHUB_ACCESS_TOKEN nor GITLAB_ACCESS_TOKEN is set".into());
+        }
 
         Ok(Self {
             client: reqwest::Client::new(),
             github_token,
+            gitlab_token,
             greptile_api_token,
         })
     }
 
+    /// Reads tokens from `config.toml`. A missing `[github]` or `[gitlab]`
+    /// section falls back to its env var; the Greptile token has no fallback.
+    fn from_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let github_token = match &config.github {
+            Some(section) => Some(Secret::new(section.access_token.clone())),
+            None => std::env::var("GITHUB_ACCESS_TOKEN").ok().map(Secret::new),
+        };
+        let gitlab_token = match &config.gitlab {
+            Some(section) => Some(Secret::new(section.access_token.clone())),
+            None => std::env::var("GITLAB_ACCESS_TOKEN").ok().map(Secret::new),
+        };
+
+        if github_token.is_none() && gitlab_token.is_none() {
+            return Err("Neither [github] nor [gitlab] access_token is set in config.toml".into());
+        }
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            github_token,
+            gitlab_token,
+            greptile_api_token: Secret::new(config.greptile.api_token.clone()),
+        })
+    }
+
+    /// Header name and token for the given remote's forge.
+    fn remote_auth_header(&self, remote: Remote) -> Result<(&'static str, &str), Box<dyn std::error::Error>> {
+        match remote {
+            Remote::Github => self
+                .github_token
+                .as_ref()
+                .map(|token| ("X-Github-Token", token.expose_secret().as_str()))
+                .ok_or_else(|| "GITHUB_ACCESS_TOKEN is not set".into()),
+            Remote::Gitlab => self
+                .gitlab_token
+                .as_ref()
+                .map(|token| ("X-Gitlab-Token", token.expose_secret().as_str()))
+                .ok_or_else(|| "GITLAB_ACCESS_TOKEN is not set".into()),
+        }
+    }
+
     async fn check_repo_exists(&self, repo_id: String) -> Result<bool, Box<dyn std::error::Error>> {
-        let url = format!("https://api.greptile.com/v2/repositories/github%253Amain%253Ashuttle-hq%252Fzero-to-production-newsletter-api");
+        let url = format!(
+            "https://api.greptile.com/v2/repositories/{}",
+            double_percent_encode(&repo_id)
+        );
         let res = self
             .client
             .get(&url)
-            .bearer_auth(&self.greptile_api_token)
+            .bearer_auth(self.greptile_api_token.expose_secret())
             .send()
             .await?;
 
@@ -113,12 +576,14 @@ impl GreptileClient {
         &self,
         req: GreptileIndexRequest,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let (header_name, token) = self.remote_auth_header(req.remote)?;
+
         let response = self
             .client
             .post("https://api.greptile.com/v2/repositories")
-            .bearer_auth(&self.greptile_api_token)
+            .bearer_auth(self.greptile_api_token.expose_secret())
             .header("Content-Type", "application/json")
-            .header("X-Github-Token", &self.github_token)
+            .header(header_name, token)
             .json(&req)
             .send()
             .await?;
@@ -134,12 +599,14 @@ impl GreptileClient {
         &self,
         req: GreptileQueryRequest,
     ) -> Result<String, Box<dyn std::error::Error>> {
+        let (header_name, token) = self.remote_auth_header(req.repositories[0].remote)?;
+
         let mut response = self
             .client
             .post("https://api.greptile.com/v2/query")
-            .bearer_auth(&self.greptile_api_token)
+            .bearer_auth(self.greptile_api_token.expose_secret())
             .header("Content-Type", "application/json")
-            .header("X-Github-Token", &self.github_token)
+            .header(header_name, token)
             .json(&req);
 
         let mut response = response.send().await?;
@@ -148,10 +615,80 @@ impl GreptileClient {
 
         Ok(response_body)
     }
+
+    /// Like [`Self::query_repo`] but prints each SSE delta as it arrives.
+    async fn query_repo_streaming(
+        &self,
+        mut req: GreptileQueryRequest,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        req.stream = true;
+
+        let (header_name, token) = self.remote_auth_header(req.repositories[0].remote)?;
+
+        let mut response = self
+            .client
+            .post("https://api.greptile.com/v2/query")
+            .bearer_auth(self.greptile_api_token.expose_secret())
+            .header("Content-Type", "application/json")
+            .header(header_name, token)
+            .json(&req)
+            .send()
+            .await?;
+
+        if response.status() != 200 {
+            return Err(response.text().await.unwrap_or_default().into());
+        }
+
+        let mut buf: Vec<u8> = Vec::new();
+        let mut full_content = String::new();
+
+        while let Some(chunk) = response.chunk().await? {
+            buf.extend_from_slice(&chunk);
+
+            while let Some(newline_pos) = buf.iter().position(|&b| b == b'\n') {
+                let line = String::from_utf8_lossy(&buf[..newline_pos])
+                    .trim_end_matches('\r')
+                    .to_string();
+                buf.drain(..=newline_pos);
+
+                let Some(data) = line.strip_prefix("data: ") else {
+                    continue;
+                };
+
+                if data == "[DONE]" {
+                    continue;
+                }
+
+                let delta: GreptileStreamDelta = serde_json::from_str(data)?;
+
+                print!("{}", delta.message);
+                use std::io::Write;
+                std::io::stdout().flush()?;
+
+                full_content.push_str(&delta.message);
+
+                if delta.finished {
+                    println!();
+                    return Ok(full_content);
+                }
+            }
+        }
+
+        println!();
+
+        Ok(full_content)
+    }
+}
+
+#[derive(Deserialize)]
+struct GreptileStreamDelta {
+    message: String,
+    #[serde(default)]
+    finished: bool,
 }
 #[derive(Serialize)]
 struct GreptileIndexRequest {
-    remote: String,
+    remote: Remote,
     repository: String,
     branch: String,
     reload: bool,
@@ -259,15 +796,31 @@ impl Display for Role {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Remote {
+    Github,
+    Gitlab,
+}
+
+impl Display for Remote {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Github => write!(f, "github"),
+            Self::Gitlab => write!(f, "gitlab"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct GreptileRepository {
-    remote: String,
+    remote: Remote,
     branch: String,
     repository: String,
 }
 
 impl GreptileRepository {
-    fn new(remote: String, branch: String, repository: String) -> Self {
+    fn new(remote: Remote, branch: String, repository: String) -> Self {
         Self {
             remote,
             branch,
@@ -280,6 +833,58 @@ impl GreptileRepository {
     }
 }
 
+/// Greptile expects repo ids double-encoded when embedded as a URL segment.
+fn double_percent_encode(repo_id: &str) -> String {
+    let once = repo_id.replace(':', "%3A").replace('/', "%2F");
+
+    once.replace('%', "%25")
+}
+
+/// Mirrors the fenced ```json block `PROMPT` asks the model to emit.
+#[derive(Serialize, Deserialize, Debug)]
+struct ConversionPlan {
+    resources: Vec<ResourcePlan>,
+    framework: String,
+    #[serde(rename = "framework-version")]
+    framework_version: Option<String>,
+    #[serde(rename = "static-files")]
+    static_files: Vec<String>,
+    secrets: Vec<String>,
+    #[serde(rename = "rust-code-changes-to-support-resources")]
+    code_changes: Vec<CodeChange>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ResourcePlan {
+    #[serde(rename = "type")]
+    kind: String,
+    flavour: Option<String>,
+    schema: Option<String>,
+    supported: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CodeChange {
+    filepath: String,
+    description: String,
+}
+
+impl ConversionPlan {
+    /// Parses the fenced ```json block out of a Greptile Markdown reply.
+    fn from_markdown(markdown: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let regex = Regex::new(r#"(?s)```json\s*(.*?)\s*```"#)?;
+
+        let Some(caps) = regex.captures(markdown) else {
+            return Err("Response did not contain a fenced ```json block".into());
+        };
+
+        let plan = serde_json::from_str(&caps[1])
+            .map_err(|err| format!("Conversion plan JSON did not match the expected schema: {err}"))?;
+
+        Ok(plan)
+    }
+}
+
 const PROMPT: &str = r#"Use this as a template to summarise the project and convert it to run on the Shuttle Rust framework and platform:
 
 **Supported Shuttle Resources:**
@@ -350,3 +955,116 @@ Important Notes:
 ```
 
 Your output should be in Markdown."#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_forge_url_matches_https_with_nested_gitlab_subgroups() {
+        let repo = parse_forge_url("gitlab.com", "https://gitlab.com/group/subgroup/project.git")
+            .unwrap();
+
+        assert_eq!(repo.as_deref(), Some("group/subgroup/project"));
+    }
+
+    #[test]
+    fn parse_forge_url_matches_ssh_form() {
+        let repo = parse_forge_url("github.com", "git@github.com:owner/repo.git").unwrap();
+
+        assert_eq!(repo.as_deref(), Some("owner/repo"));
+    }
+
+    #[test]
+    fn parse_forge_url_returns_none_for_other_hosts() {
+        let repo = parse_forge_url("gitlab.com", "https://github.com/owner/repo.git").unwrap();
+
+        assert_eq!(repo, None);
+    }
+
+    #[test]
+    fn double_percent_encode_escapes_colon_and_slash_twice() {
+        let encoded = double_percent_encode("github:main:shuttle-hq/zero-to-production-newsletter-api");
+
+        assert_eq!(
+            encoded,
+            "github%253Amain%253Ashuttle-hq%252Fzero-to-production-newsletter-api"
+        );
+    }
+
+    #[test]
+    fn conversion_plan_from_markdown_parses_fenced_json_block() {
+        let markdown = r#"Here is the plan:
+
+```json
+{
+  "resources": [
+    { "type": "database", "flavour": "postgres", "supported": true }
+  ],
+  "framework": "actix-web",
+  "static-files": ["src/routes/login/home.html"],
+  "secrets": ["APP_DATABASE__PASSWORD"],
+  "rust-code-changes-to-support-resources": [
+    { "filepath": "src/main.rs", "description": "Use shuttle_runtime::main." }
+  ]
+}
+```
+
+Some trailing prose."#;
+
+        let plan = ConversionPlan::from_markdown(markdown).unwrap();
+
+        assert_eq!(plan.framework, "actix-web");
+        assert_eq!(plan.resources[0].kind, "database");
+        assert_eq!(plan.secrets, vec!["APP_DATABASE__PASSWORD".to_string()]);
+        assert_eq!(plan.code_changes[0].filepath, "src/main.rs");
+    }
+
+    #[test]
+    fn conversion_plan_from_markdown_errors_without_json_block() {
+        assert!(ConversionPlan::from_markdown("Just plain Markdown, no code fence.").is_err());
+    }
+
+    #[test]
+    fn verify_github_signature_accepts_matching_hmac() {
+        use hmac::Mac;
+
+        let secret = b"shhh";
+        let body = b"{\"ref\":\"refs/heads/main\"}";
+
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(body);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(verify_github_signature(secret, body, &signature));
+    }
+
+    #[test]
+    fn verify_github_signature_rejects_tampered_body() {
+        use hmac::Mac;
+
+        let secret = b"shhh";
+        let mut mac = HmacSha256::new_from_slice(secret).unwrap();
+        mac.update(b"original body");
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(!verify_github_signature(secret, b"tampered body", &signature));
+    }
+
+    #[test]
+    fn verify_github_signature_rejects_wrong_secret() {
+        use hmac::Mac;
+
+        let body = b"same body";
+        let mut mac = HmacSha256::new_from_slice(b"right-secret").unwrap();
+        mac.update(body);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        assert!(!verify_github_signature(b"wrong-secret", body, &signature));
+    }
+
+    #[test]
+    fn verify_github_signature_rejects_missing_prefix() {
+        assert!(!verify_github_signature(b"secret", b"body", "deadbeef"));
+    }
+}